@@ -0,0 +1,232 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::{stderr, stdout, Write};
+use std::panic::Location;
+
+/// Panics in debug builds, naming its prepare site, if dropped before `run` was invoked on its operation.
+struct DropBomb {
+    prepared_at: &'static Location<'static>,
+    armed: bool,
+}
+
+impl DropBomb {
+    fn new(prepared_at: &'static Location<'static>) -> Self {
+        DropBomb { prepared_at, armed: true }
+    }
+
+    fn defuse(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for DropBomb {
+    fn drop(&mut self) {
+        if self.armed && !std::thread::panicking() && cfg!(debug_assertions) {
+            panic!(
+                "operation prepared at {} was dropped without its `run` ever being invoked",
+                self.prepared_at
+            );
+        }
+    }
+}
+
+/// Invoke `cmd.run`, defusing `bomb` and annotating any error with both the prepare and run call sites.
+#[track_caller]
+fn run_guarded(
+    cmd: impl Command,
+    bomb: DropBomb,
+    progress: Option<prodash::tree::Item>,
+    out: &mut dyn Write,
+    err: &mut dyn Write,
+) -> Result<()> {
+    let executed_at = Location::caller();
+    let prepared_at = bomb.prepared_at;
+    let res = cmd.run(progress, out, err);
+    bomb.defuse();
+    res.with_context(|| format!("operation prepared at {} and run at {}", prepared_at, executed_at))
+}
+
+/// The file descriptor that `progress_format_json` writes to, from `GITOXIDE_PROGRESS_FD` or stderr.
+fn progress_json_fd() -> std::os::unix::io::RawFd {
+    std::env::var("GITOXIDE_PROGRESS_FD")
+        .ok()
+        .and_then(|fd| fd.parse().ok())
+        .unwrap_or(2)
+}
+
+#[derive(serde::Serialize)]
+struct ProgressEvent {
+    name: String,
+    step: usize,
+    total: Option<usize>,
+    unit: Option<String>,
+    depth: usize,
+}
+
+/// Poll `tree` at `frame_rate` and write one JSON object per progress node to `fd` until it empties.
+fn spawn_json_progress_emitter(
+    tree: prodash::Tree,
+    fd: std::os::unix::io::RawFd,
+    frame_rate: f32,
+) -> std::thread::JoinHandle<()> {
+    use std::os::unix::io::FromRawFd;
+    // `fd` (stderr by default) is owned by the rest of the process, not by us: wrapping it directly in a
+    // `File` would close it out from under every other writer once this thread's loop ends. Duplicate it
+    // first so only our private copy gets closed when the emitter is done.
+    let fd = unsafe { libc::dup(fd) };
+    std::thread::spawn(move || {
+        let mut out = unsafe { std::fs::File::from_raw_fd(fd) };
+        let interval = std::time::Duration::from_secs_f32(1.0 / frame_rate);
+        loop {
+            let mut saw_any = false;
+            for (depth, progress) in tree.nodes() {
+                saw_any = true;
+                let event = ProgressEvent {
+                    name: progress.name.clone(),
+                    step: progress.step,
+                    total: progress.done_at,
+                    unit: progress.unit.as_ref().map(ToString::to_string),
+                    depth,
+                };
+                if let Ok(line) = serde_json::to_string(&event) {
+                    let _ = writeln!(out, "{}", line);
+                }
+            }
+            if !saw_any {
+                break;
+            }
+            std::thread::sleep(interval);
+        }
+    })
+}
+
+/// Implemented by every plumbing or porcelain subcommand so it can be driven by a single dispatcher.
+pub trait Command {
+    /// A short, human-readable name used for this command's progress tree and TUI title.
+    fn name(&self) -> &str;
+
+    /// Run the command to completion, optionally reporting progress through `progress`.
+    fn run(self, progress: Option<prodash::tree::Item>, out: &mut dyn Write, err: &mut dyn Write) -> Result<()>;
+}
+
+/// Flags shared by every command for controlling how progress is surfaced.
+#[derive(Debug, Default)]
+pub struct ProgressOptions {
+    /// verbose progress messages are printed line by line
+    pub verbose: bool,
+    /// bring up a terminal user interface displaying progress visually
+    pub progress: bool,
+    /// the progress TUI will stay up even though the work is already completed
+    pub progress_keep_open: bool,
+    /// emit progress as newline-delimited JSON on `GITOXIDE_PROGRESS_FD` (default stderr)
+    pub progress_format_json: bool,
+    /// overrides `crate::shared::DEFAULT_FRAME_RATE` for the progress TUI and JSON emitter, if set
+    pub frame_rate: Option<f32>,
+}
+
+impl ProgressOptions {
+    /// Fill in any flag still at its default from `config`; CLI flags always win.
+    pub fn with_config_defaults(mut self, config: &crate::config::Config) -> Self {
+        if !self.verbose && !self.progress {
+            self.verbose = config.verbose.unwrap_or(false);
+            self.progress = config.progress.unwrap_or(false);
+        }
+        self.progress_keep_open = self.progress_keep_open || config.progress_keep_open.unwrap_or(false);
+        self.frame_rate = self.frame_rate.or(config.frame_rate);
+        self
+    }
+
+    fn frame_rate(&self) -> f32 {
+        self.frame_rate.unwrap_or(crate::shared::DEFAULT_FRAME_RATE)
+    }
+}
+
+/// Drive `cmd` to completion, wiring up the line renderer, TUI, or JSON progress emitter per `progress_options`.
+#[track_caller]
+pub fn dispatch(cmd: impl Command + Send + 'static, progress_options: ProgressOptions) -> Result<()> {
+    let prepared_at = Location::caller();
+    let frame_rate = progress_options.frame_rate();
+    let ProgressOptions {
+        verbose,
+        progress,
+        progress_keep_open,
+        progress_format_json,
+        ..
+    } = progress_options;
+    let name = cmd.name().to_owned();
+    match (verbose, progress) {
+        (false, false) => {
+            let bomb = DropBomb::new(prepared_at);
+            if progress_format_json {
+                let tree = prodash::Tree::new();
+                let sub_progress = tree.add_child(&name);
+                let _handle = spawn_json_progress_emitter(tree, progress_json_fd(), frame_rate);
+                run_guarded(cmd, bomb, Some(sub_progress), &mut stdout(), &mut stderr())
+            } else {
+                run_guarded(cmd, bomb, None, &mut stdout(), &mut stderr())
+            }
+        }
+        (true, false) => {
+            let bomb = DropBomb::new(prepared_at);
+            let tree = prodash::Tree::new();
+            let sub_progress = tree.add_child(&name);
+            let _json_handle = if progress_format_json {
+                Some(spawn_json_progress_emitter(tree.clone(), progress_json_fd(), frame_rate))
+            } else {
+                None
+            };
+            let _handle = crate::shared::setup_line_renderer(tree, 2);
+            run_guarded(cmd, bomb, Some(sub_progress), &mut stdout(), &mut stderr())
+        }
+        (true, true) | (false, true) => {
+            enum Event {
+                UIDone,
+                ComputationDone(Result<()>, Vec<u8>, Vec<u8>),
+            };
+            let bomb = DropBomb::new(prepared_at);
+            let tree = prodash::Tree::new();
+            let sub_progress = tree.add_child(&name);
+            let _json_handle = if progress_format_json {
+                Some(spawn_json_progress_emitter(tree.clone(), progress_json_fd(), frame_rate))
+            } else {
+                None
+            };
+            let render_tui = prodash::tui::render(
+                stdout(),
+                tree,
+                prodash::tui::Options {
+                    title: name,
+                    frames_per_second: frame_rate,
+                    stop_if_empty_progress: !progress_keep_open,
+                    ..Default::default()
+                },
+            )
+            .expect("tui to come up without io error");
+            let (tx, rx) = std::sync::mpsc::sync_channel::<Event>(1);
+            let ui_handle = std::thread::spawn({
+                let tx = tx.clone();
+                move || {
+                    smol::run(render_tui);
+                    tx.send(Event::UIDone).ok();
+                }
+            });
+            std::thread::spawn(move || {
+                // We might have something interesting to show, which would be hidden by the alternate screen if there is a progress TUI
+                // We know that the printing happens at the end, so this is fine.
+                let mut out = Vec::new();
+                let mut err = Vec::new();
+                let res = run_guarded(cmd, bomb, Some(sub_progress), &mut out, &mut err);
+                tx.send(Event::ComputationDone(res, out, err)).ok();
+            });
+            match rx.recv() {
+                Ok(Event::UIDone) => Err(anyhow!("Operation cancelled by user")),
+                Ok(Event::ComputationDone(res, out, err)) => {
+                    ui_handle.join().ok();
+                    stdout().write_all(&out)?;
+                    stderr().write_all(&err)?;
+                    res
+                }
+                _ => Err(anyhow!("Error communicating with threads")),
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Durable user preferences loaded from a TOML file in the platform config directory; CLI flags win over these.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// default for `--format`, parsed against `core::OutputFormat::variants()`
+    pub format: Option<String>,
+    /// default for `--verbose`
+    pub verbose: Option<bool>,
+    /// default for `--progress`
+    pub progress: Option<bool>,
+    /// default for `--progress-keep-open`
+    pub progress_keep_open: Option<bool>,
+    /// overrides `crate::shared::DEFAULT_FRAME_RATE` for the progress TUI
+    pub frame_rate: Option<f32>,
+}
+
+impl Config {
+    /// Load the user's config file, falling back to built-in defaults if it's missing or invalid.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Where the config file lives on this platform, if a home directory could be determined.
+    pub fn path() -> Option<PathBuf> {
+        Self::project_dirs().map(|dirs| dirs.config_dir().join("config.toml"))
+    }
+
+    /// The directory gitoxide may use for cached objects and packs, alongside the config file.
+    pub fn cache_dir() -> Option<PathBuf> {
+        Self::project_dirs().map(|dirs| dirs.cache_dir().to_owned())
+    }
+
+    fn project_dirs() -> Option<directories::ProjectDirs> {
+        directories::ProjectDirs::from("", "", "gitoxide")
+    }
+}
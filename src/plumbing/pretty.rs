@@ -1,8 +1,10 @@
 use anyhow::{anyhow, Result};
 use gitoxide_core as core;
-use std::io::{stderr, stdout, Write};
+use std::io::Write;
 use structopt::StructOpt;
 
+use crate::command::{self, Command, ProgressOptions};
+use crate::config::Config;
 use options::*;
 
 mod options {
@@ -28,13 +30,8 @@ mod options {
             #[structopt(long, short = "s")]
             statistics: bool,
             /// Determine the format to use when outputting statistics.
-            #[structopt(
-                long,
-                short = "f",
-                default_value = "human",
-                possible_values(core::OutputFormat::variants())
-            )]
-            format: core::OutputFormat,
+            #[structopt(long, short = "f", possible_values(core::OutputFormat::variants()))]
+            format: Option<core::OutputFormat>,
 
             /// verbose progress messages are printed line by line
             #[structopt(long, short = "v")]
@@ -50,99 +47,163 @@ mod options {
             #[structopt(long, conflicts_with("verbose"), requires("progress"))]
             progress_keep_open: bool,
 
-            /// The '.pack' or '.idx' file whose checksum to validate.
-            #[structopt(parse(from_os_str))]
-            path: PathBuf,
+            /// Emit progress as newline-delimited JSON instead of (or alongside) the human-facing renderer.
+            ///
+            /// Each line is a self-contained JSON object describing one progress update: the task name, its
+            /// current/total step and unit, and its position in the task tree. This is meant for editors or CI
+            /// wrappers that want to render their own UI instead of the built-in line renderer or TUI. Lines are
+            /// written to the file descriptor named by `GITOXIDE_PROGRESS_FD`, defaulting to standard error.
+            #[structopt(long)]
+            progress_format_json: bool,
+
+            /// Keep verifying the remaining paths even if one of them fails, instead of aborting immediately.
+            #[structopt(long)]
+            no_fail_fast: bool,
+
+            /// The '.pack' or '.idx' file(s) whose checksum to validate, or a directory to scan for such files.
+            #[structopt(required = true, parse(from_os_str))]
+            paths: Vec<PathBuf>,
         },
     }
 }
 
-fn prepare_and_run<T: Send + 'static>(
-    name: &str,
-    verbose: bool,
-    progress: bool,
-    progress_keep_open: bool,
-    run: impl FnOnce(Option<prodash::tree::Item>, &mut dyn std::io::Write, &mut dyn std::io::Write) -> Result<T>
-        + Send
-        + 'static,
-) -> Result<T> {
-    super::init_env_logger(false);
-    match (verbose, progress) {
-        (false, false) => run(None, &mut stdout(), &mut stderr()),
-        (true, false) => {
-            let progress = prodash::Tree::new();
-            let sub_progress = progress.add_child(name);
-            let _handle = crate::shared::setup_line_renderer(progress, 2);
-            run(Some(sub_progress), &mut stdout(), &mut stderr())
-        }
-        (true, true) | (false, true) => {
-            enum Event<T> {
-                UIDone,
-                ComputationDone(Result<T>, Vec<u8>, Vec<u8>),
-            };
-            let progress = prodash::Tree::new();
-            let sub_progress = progress.add_child(name);
-            let render_tui = prodash::tui::render(
-                stdout(),
-                progress,
-                prodash::tui::Options {
-                    title: "gitoxide".into(),
-                    frames_per_second: crate::shared::DEFAULT_FRAME_RATE,
-                    stop_if_empty_progress: !progress_keep_open,
-                    ..Default::default()
-                },
-            )
-            .expect("tui to come up without io error");
-            let (tx, rx) = std::sync::mpsc::sync_channel::<Event<T>>(1);
-            let ui_handle = std::thread::spawn({
-                let tx = tx.clone();
-                move || {
-                    smol::run(render_tui);
-                    tx.send(Event::UIDone).ok();
+fn find_pack_or_index_files(paths: Vec<std::path::PathBuf>) -> Result<Vec<std::path::PathBuf>> {
+    let mut out = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            for entry in std::fs::read_dir(&path)? {
+                let entry_path = entry?.path();
+                match entry_path.extension().and_then(|e| e.to_str()) {
+                    Some("pack") | Some("idx") => out.push(entry_path),
+                    _ => continue,
                 }
-            });
-            std::thread::spawn(move || {
-                // We might have something interesting to show, which would be hidden by the alternate screen if there is a progress TUI
-                // We know that the printing happens at the end, so this is fine.
-                let mut out = Vec::new();
-                let mut err = Vec::new();
-                let res = run(Some(sub_progress), &mut out, &mut err);
-                tx.send(Event::ComputationDone(res, out, err)).ok();
-            });
-            match rx.recv() {
-                Ok(Event::UIDone) => Err(anyhow!("Operation cancelled by user")),
-                Ok(Event::ComputationDone(res, out, err)) => {
-                    ui_handle.join().ok();
-                    stdout().write_all(&out)?;
-                    stderr().write_all(&err)?;
-                    res
+            }
+        } else {
+            out.push(path);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_pack_or_index_files;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A scratch directory under `std::env::temp_dir()` removed again on drop, so tests don't need an extra
+    /// dependency just to set up fixture files.
+    struct ScratchDir(PathBuf);
+
+    impl ScratchDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("gio-plumbing-test-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&path).expect("can create scratch dir");
+            ScratchDir(path)
+        }
+    }
+
+    impl Drop for ScratchDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn expands_a_directory_to_its_pack_and_idx_files_only() {
+        let dir = ScratchDir::new("mixed-dir");
+        for name in &["a.pack", "a.idx", "a.keep", "README.md"] {
+            fs::write(dir.0.join(name), b"").expect("can write fixture file");
+        }
+
+        let mut found = find_pack_or_index_files(vec![dir.0.clone()]).expect("directory can be scanned");
+        found.sort();
+
+        let mut expected = vec![dir.0.join("a.idx"), dir.0.join("a.pack")];
+        expected.sort();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn passes_plain_file_paths_through_unchanged() {
+        let dir = ScratchDir::new("plain-file");
+        let pack = dir.0.join("explicit.pack");
+        fs::write(&pack, b"").expect("can write fixture file");
+
+        let found = find_pack_or_index_files(vec![pack.clone()]).expect("file path is not scanned as a directory");
+        assert_eq!(found, vec![pack]);
+    }
+}
+
+struct VerifyPack {
+    paths: Vec<std::path::PathBuf>,
+    format: core::OutputFormat,
+    statistics: bool,
+    no_fail_fast: bool,
+}
+
+impl Command for VerifyPack {
+    fn name(&self) -> &str {
+        "verify-pack"
+    }
+
+    fn run(self, mut progress: Option<prodash::tree::Item>, out: &mut dyn Write, err: &mut dyn Write) -> Result<()> {
+        let paths = find_pack_or_index_files(self.paths)?;
+        let num_packs = paths.len();
+        let statistics = if self.statistics { Some(self.format) } else { None };
+        let mut failures = 0usize;
+        for path in paths {
+            let sub_progress = progress.as_mut().map(|p| p.add_child(path.display().to_string()));
+            match core::verify_pack_or_pack_index(path, sub_progress, statistics, out, err) {
+                Ok(_) => {}
+                Err(e) if self.no_fail_fast => {
+                    failures += 1;
+                    writeln!(err, "{}", e)?;
                 }
-                _ => Err(anyhow!("Error communicating with threads")),
+                Err(e) => return Err(e),
             }
         }
+        if failures > 0 {
+            return Err(anyhow!("{} out of {} packs failed verification", failures, num_packs));
+        }
+        Ok(())
     }
 }
 
 pub fn main() -> Result<()> {
+    super::init_env_logger(false);
+    let config = Config::load();
     let args = Args::from_args();
     match args.cmd {
         Subcommands::VerifyPack {
-            path,
+            paths,
             verbose,
             progress,
             format,
             progress_keep_open,
+            progress_format_json,
             statistics,
-        } => prepare_and_run(
-            "verify-pack",
-            verbose,
-            progress,
-            progress_keep_open,
-            move |progress, out, err| {
-                core::verify_pack_or_pack_index(path, progress, if statistics { Some(format) } else { None }, out, err)
-            },
-        )
-        .map(|_| ()),
-    }?;
-    Ok(())
+            no_fail_fast,
+        } => {
+            let format = format
+                .or_else(|| config.format.as_deref().and_then(|f| f.parse().ok()))
+                .unwrap_or(core::OutputFormat::Human);
+            command::dispatch(
+                VerifyPack {
+                    paths,
+                    format,
+                    statistics,
+                    no_fail_fast,
+                },
+                ProgressOptions {
+                    verbose,
+                    progress,
+                    progress_keep_open,
+                    progress_format_json,
+                    frame_rate: None,
+                }
+                .with_config_defaults(&config),
+            )
+        }
+    }
 }
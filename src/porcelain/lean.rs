@@ -22,11 +22,28 @@ mod options {
 
 use anyhow::Result;
 use gitoxide_core as core;
+use std::io::Write;
+
+use crate::command::{self, Command, ProgressOptions};
+use crate::config::Config;
+
+impl Command for options::Init {
+    fn name(&self) -> &str {
+        "init"
+    }
+
+    fn run(self, _progress: Option<prodash::tree::Item>, _out: &mut dyn Write, _err: &mut dyn Write) -> Result<()> {
+        core::init()
+    }
+}
 
 pub fn main() -> Result<()> {
     pub use options::*;
+    let config = Config::load();
     let cli: Args = argh::from_env();
     match cli.subcommand {
-        SubCommands::Init(_) => core::init(),
+        SubCommands::Init(cmd) => {
+            command::dispatch(cmd, ProgressOptions::default().with_config_defaults(&config))
+        }
     }
 }